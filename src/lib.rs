@@ -1,33 +1,29 @@
-use std::{collections::HashMap, vec};
+#[allow(clippy::module_inception)]
+mod rule;
+
+use rand::Rng;
+use std::{
+    collections::{HashMap, VecDeque},
+    vec,
+};
 
 #[derive(Debug, PartialEq, Eq)]
-struct Tree {
-    name: String,
-    children: HashMap<String, Tree>,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Tree<V> {
+    data: V,
+    children: HashMap<String, Tree<V>>,
 }
 
-impl Tree {
-    fn default() -> Self {
-        Tree::new("root".to_string())
-    }
-
-    fn new(name: String) -> Tree {
-        Tree {
-            name,
-            children: HashMap::default(),
-        }
-    }
-
-    fn add_phrase(&mut self, phrase: Vec<String>) {
-        let mut node = self;
-        for subkey in phrase {
-            node = node
-                .children
-                .entry(subkey.to_string())
-                .or_insert_with(|| Tree::new(subkey));
-        }
-    }
+#[derive(Debug, PartialEq, Eq)]
+pub enum RemovePhraseError {
+    /// The phrase does not exist in the tree at all.
+    NotFound,
+    /// The phrase exists but is itself a prefix of a longer stored phrase,
+    /// so it still has descendants and cannot be pruned.
+    StillHasDescendants,
+}
 
+impl<V> Tree<V> {
     fn children_names(&self) -> Vec<String> {
         self.children.keys().cloned().collect()
     }
@@ -36,6 +32,14 @@ impl Tree {
         self.children.get(&name)
     }
 
+    pub fn data(&self) -> &V {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut V {
+        &mut self.data
+    }
+
     fn split_sentence(sentence: String) -> Vec<String> {
         sentence
             .split_ascii_whitespace()
@@ -43,17 +47,6 @@ impl Tree {
             .collect()
     }
 
-    pub fn from_corpus(corpus: String) -> Self {
-        let mut tree = Tree::default();
-        let sentences = split_corpus(corpus);
-        for sentence in sentences {
-            for suffix in suffixes(Self::split_sentence(sentence)) {
-                tree.add_phrase(suffix)
-            }
-        }
-        tree
-    }
-
     pub fn names_at_path(&self, path: Vec<String>) -> Option<Vec<String>> {
         let mut node = self;
         for k in path {
@@ -91,9 +84,226 @@ impl Tree {
             })
             .collect()
     }
+
+    pub fn flatten(&self) -> Vec<Vec<String>> {
+        let mut acc = vec![];
+        self.flatten_into(vec![], &mut acc);
+        acc
+    }
+
+    fn flatten_into(&self, prefix: Vec<String>, acc: &mut Vec<Vec<String>>) {
+        for (name, child) in &self.children {
+            let mut path = prefix.clone();
+            path.push(name.clone());
+            acc.push(path.clone());
+            child.flatten_into(path, acc);
+        }
+    }
+
+    pub fn longest_matching_prefix(&self, path: Vec<String>) -> (Vec<String>, Vec<String>) {
+        let mut node = self;
+        let mut matched = vec![];
+        for k in path {
+            match node.step_down(k.clone()) {
+                Some(next) => {
+                    matched.push(k);
+                    node = next;
+                }
+                None => break,
+            }
+        }
+        (matched, node.children_names())
+    }
+
+    pub fn completions(&self, prefix: Vec<String>) -> Vec<Vec<String>> {
+        let mut node = self;
+        for k in prefix {
+            match node.step_down(k) {
+                Some(next) => node = next,
+                None => return vec![],
+            }
+        }
+        node.flatten()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Vec<String>> + '_ {
+        let mut queue = VecDeque::new();
+        for (name, child) in &self.children {
+            queue.push_back((vec![name.clone()], child));
+        }
+        TreeIter { queue }
+    }
+}
+
+struct TreeIter<'a, V> {
+    queue: VecDeque<(Vec<String>, &'a Tree<V>)>,
+}
+
+impl<'a, V> Iterator for TreeIter<'a, V> {
+    type Item = Vec<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, node) = self.queue.pop_front()?;
+        for (name, child) in &node.children {
+            let mut child_path = path.clone();
+            child_path.push(name.clone());
+            self.queue.push_back((child_path, child));
+        }
+        Some(path)
+    }
+}
+
+impl<V: Default> Tree<V> {
+    fn default() -> Self {
+        Tree::new(V::default())
+    }
+
+    fn new(data: V) -> Tree<V> {
+        Tree {
+            data,
+            children: HashMap::default(),
+        }
+    }
+
+    fn add_phrase(&mut self, phrase: Vec<String>) {
+        let mut node = self;
+        for subkey in phrase {
+            node = node.children.entry(subkey).or_insert_with(Tree::default);
+        }
+    }
+
+    pub fn remove_phrase(&mut self, phrase: Vec<String>) -> Result<Vec<String>, RemovePhraseError> {
+        {
+            let mut node = &*self;
+            for subkey in &phrase {
+                node = node
+                    .step_down(subkey.clone())
+                    .ok_or(RemovePhraseError::NotFound)?;
+            }
+            if !node.children.is_empty() {
+                return Err(RemovePhraseError::StillHasDescendants);
+            }
+        }
+        prune(self, &phrase);
+        Ok(phrase)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<V: serde::Serialize> Tree<V> {
+    pub fn to_writer<W: std::io::Write>(&self, writer: W) -> serde_json::Result<()> {
+        serde_json::to_writer(writer, self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<V: serde::de::DeserializeOwned> Tree<V> {
+    pub fn from_reader<R: std::io::Read>(reader: R) -> serde_json::Result<Self> {
+        serde_json::from_reader(reader)
+    }
+}
+
+impl Tree<usize> {
+    pub fn from_corpus(corpus: String) -> Self {
+        let mut tree = Tree::default();
+        let sentences = split_corpus(corpus);
+        for sentence in sentences {
+            for suffix in suffixes(Self::split_sentence(sentence)) {
+                let mut node = &mut tree;
+                for subkey in suffix {
+                    node = node.children.entry(subkey).or_insert_with(Tree::default);
+                    *node.data_mut() += 1;
+                }
+            }
+        }
+        tree
+    }
+
+    pub fn occurrences(&self, phrase: Vec<String>) -> usize {
+        let mut node = self;
+        for k in phrase {
+            match node.step_down(k) {
+                Some(next) => node = next,
+                None => return 0,
+            }
+        }
+        *node.data()
+    }
+
+    pub fn continuation_distribution(&self, path: Vec<String>) -> Option<HashMap<String, f64>> {
+        let mut node = self;
+        for k in path {
+            node = node.step_down(k)?;
+        }
+        let total: usize = node.children.values().map(|child| *child.data()).sum();
+        if total == 0 {
+            return None;
+        }
+        Some(
+            node.children
+                .iter()
+                .map(|(name, child)| (name.clone(), *child.data() as f64 / total as f64))
+                .collect(),
+        )
+    }
+
+    pub fn generate(&self, seed: Vec<String>, max_len: usize, rng: &mut impl Rng) -> Vec<String> {
+        let mut result = seed;
+        while result.len() < max_len {
+            let mut window = result.clone();
+            let distribution = loop {
+                match self.continuation_distribution(window.clone()) {
+                    Some(dist) if !dist.is_empty() => break Some(dist),
+                    _ if window.is_empty() => break None,
+                    _ => {
+                        window.remove(0);
+                    }
+                }
+            };
+            match distribution {
+                Some(dist) => result.push(sample(&dist, rng)),
+                None => break,
+            }
+        }
+        result
+    }
+}
+
+fn sample(distribution: &HashMap<String, f64>, rng: &mut impl Rng) -> String {
+    let mut roll: f64 = rng.gen();
+    for (name, probability) in distribution {
+        if roll < *probability {
+            return name.clone();
+        }
+        roll -= probability;
+    }
+    distribution
+        .keys()
+        .next()
+        .cloned()
+        .expect("distribution is checked non-empty before sampling")
+}
+
+fn prune<V>(node: &mut Tree<V>, path: &[String]) -> bool {
+    if path.is_empty() {
+        return false;
+    }
+    let should_remove_child = if path.len() == 1 {
+        true
+    } else {
+        let child = node
+            .children
+            .get_mut(&path[0])
+            .expect("existence already verified by remove_phrase");
+        prune(child, &path[1..])
+    };
+    if should_remove_child {
+        node.children.remove(&path[0]);
+    }
+    node.children.is_empty()
 }
 
-fn get_depth(t: &Tree) -> usize {
+fn get_depth<V>(t: &Tree<V>) -> usize {
     if t.children_names().len() == 0 {
         0
     } else {
@@ -134,45 +344,85 @@ fn split_corpus(x: String) -> Vec<String> {
         .collect()
 }
 
+/// Returns every start token index where `pattern` occurs in `text`, found via
+/// a Knuth-Morris-Pratt scan, which makes at most `2 * text.len()` token
+/// comparisons in total. Overlapping occurrences are all reported.
+pub fn kmp_search(text: &[String], pattern: &[String]) -> Vec<usize> {
+    if pattern.is_empty() {
+        return vec![];
+    }
+    let prefix_table = kmp_prefix_table(pattern);
+    let mut idx = 0;
+    let mut hits = vec![];
+    for (i, word) in text.iter().enumerate() {
+        while idx > 0 && pattern[idx] != *word {
+            idx = prefix_table[idx - 1];
+        }
+        if pattern[idx] == *word {
+            idx += 1;
+        }
+        if idx == pattern.len() {
+            hits.push(i + 1 - pattern.len());
+            idx = prefix_table[idx - 1];
+        }
+    }
+    hits
+}
+
+fn kmp_prefix_table(pattern: &[String]) -> Vec<usize> {
+    let mut pr = vec![0; pattern.len()];
+    let mut k = 0;
+    for i in 1..pattern.len() {
+        while k > 0 && pattern[k] != pattern[i] {
+            k = pr[k - 1];
+        }
+        if pattern[k] == pattern[i] {
+            k += 1;
+        }
+        pr[i] = k;
+    }
+    pr
+}
+
 #[cfg(test)]
 mod tests {
     use std::vec;
 
-    use crate::{split_corpus, suffixes, Tree};
+    use rand::rngs::mock::StepRng;
+
+    use crate::{kmp_search, split_corpus, suffixes, RemovePhraseError, Tree};
 
     #[test]
     fn it_defaults() {
-        let result = Tree::default();
-        assert_eq!(result.name, "root".to_string());
+        let result: Tree<()> = Tree::default();
+        assert_eq!(result.data(), &());
         assert_eq!(result.children.len(), 0);
     }
 
     #[test]
     fn it_is_new() {
         let result = Tree::new("Gerald".to_string());
-        assert_eq!(result.name, "Gerald".to_string());
+        assert_eq!(result.data(), &"Gerald".to_string());
         assert_eq!(result.children.len(), 0);
     }
 
     #[test]
     fn it_ingests_a_phrase_of_length_one() {
-        let mut result = Tree::default();
+        let mut result: Tree<()> = Tree::default();
         result.add_phrase(vec!["a".to_string()]);
-        assert_eq!(result.name, "root".to_string());
         assert_eq!(result.children.len(), 1);
     }
 
     #[test]
     fn it_ingests_a_phrase_of_length_two() {
-        let mut result = Tree::default();
+        let mut result: Tree<()> = Tree::default();
         result.add_phrase(vec!["a".to_string(), "b".to_string()]);
-        assert_eq!(result.name, "root".to_string());
         assert_eq!(result.children.len(), 1);
     }
 
     #[test]
     fn it_steps_down_a_word_that_is_there() {
-        let mut result = Tree::default();
+        let mut result: Tree<()> = Tree::default();
         result.add_phrase(vec!["a".to_string(), "b".to_string()]);
         let last = result.step_down("a".to_string());
         assert_eq!(last.unwrap().children_names(), vec!["b".to_string()]);
@@ -180,7 +430,7 @@ mod tests {
 
     #[test]
     fn it_steps_down_a_word_that_is_not_there() {
-        let mut result = Tree::default();
+        let mut result: Tree<()> = Tree::default();
         result.add_phrase(vec!["a".to_string(), "b".to_string()]);
         let last = result.step_down("c".to_string());
         assert_eq!(last, None);
@@ -188,17 +438,16 @@ mod tests {
 
     #[test]
     fn it_exposess_children_names() {
-        let mut result = Tree::default();
+        let mut result: Tree<()> = Tree::default();
         result.add_phrase(vec!["a".to_string()]);
         assert_eq!(result.children_names(), vec!["a".to_string()]);
     }
 
     #[test]
     fn it_ingests_multiple_phrases() {
-        let mut result = Tree::default();
+        let mut result: Tree<()> = Tree::default();
         result.add_phrase(vec!["a".to_string(), "b".to_string()]);
         result.add_phrase(vec!["a".to_string(), "c".to_string()]);
-        assert_eq!(result.name, "root".to_string());
         assert_eq!(result.children_names(), vec!["a".to_string()]);
         let a_tree = result.step_down("a".to_string()).unwrap();
         assert_eq!(a_tree.children_names().len(), 2);
@@ -354,4 +603,218 @@ mod tests {
         assert_eq!(m[&"d".to_string()], 1);
         assert_eq!(m[&"e".to_string()], 0);
     }
+
+    #[test]
+    fn it_counts_occurrences_on_from_corpus() {
+        let t = Tree::from_corpus("a b. a c.".to_string());
+        let a_tree = t.step_down("a".to_string()).unwrap();
+        assert_eq!(*a_tree.data(), 2);
+        assert_eq!(*a_tree.step_down("b".to_string()).unwrap().data(), 1);
+    }
+
+    #[test]
+    fn it_counts_occurrences_of_a_phrase() {
+        let t = Tree::from_corpus("a b c. a b d. x a b.".to_string());
+
+        assert_eq!(t.occurrences(vec!["a".to_string(), "b".to_string()]), 3);
+        assert_eq!(t.occurrences(vec!["a".to_string()]), 3);
+        assert_eq!(
+            t.occurrences(vec!["a".to_string(), "b".to_string(), "c".to_string()]),
+            1
+        );
+    }
+
+    #[test]
+    fn it_counts_zero_occurrences_for_a_missing_phrase() {
+        let t = Tree::from_corpus("a b c.".to_string());
+
+        assert_eq!(t.occurrences(vec!["z".to_string()]), 0);
+    }
+
+    #[test]
+    fn it_finds_every_start_index_of_a_kmp_pattern() {
+        let text: Vec<String> = "a b a b c a b"
+            .split_ascii_whitespace()
+            .map(|x| x.to_string())
+            .collect();
+        let pattern: Vec<String> = "a b".split_ascii_whitespace().map(|x| x.to_string()).collect();
+
+        assert_eq!(kmp_search(&text, &pattern), vec![0, 2, 5]);
+    }
+
+    #[test]
+    fn it_finds_no_kmp_matches_when_the_pattern_is_absent() {
+        let text: Vec<String> = "a b c".split_ascii_whitespace().map(|x| x.to_string()).collect();
+        let pattern: Vec<String> = "c b".split_ascii_whitespace().map(|x| x.to_string()).collect();
+
+        assert_eq!(kmp_search(&text, &pattern), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn it_builds_a_continuation_distribution() {
+        let t = Tree::from_corpus("a b. a b. a c.".to_string());
+
+        let dist = t.continuation_distribution(vec!["a".to_string()]).unwrap();
+        assert_eq!(dist[&"b".to_string()], 2.0 / 3.0);
+        assert_eq!(dist[&"c".to_string()], 1.0 / 3.0);
+    }
+
+    #[test]
+    fn it_returns_none_for_a_distribution_with_no_children() {
+        let t = Tree::from_corpus("a b.".to_string());
+
+        assert_eq!(
+            t.continuation_distribution(vec!["a".to_string(), "b".to_string()]),
+            None
+        );
+    }
+
+    #[test]
+    fn it_generates_by_falling_back_to_shorter_contexts() {
+        let t = Tree::from_corpus("a b c. b c d.".to_string());
+        let mut rng = StepRng::new(0, 1);
+
+        let generated = t.generate(vec!["x".to_string(), "b".to_string()], 4, &mut rng);
+        assert_eq!(generated.len(), 4);
+        assert_eq!(generated[0], "x".to_string());
+        assert_eq!(generated[1], "b".to_string());
+    }
+
+    #[test]
+    fn it_removes_a_leaf_phrase_and_prunes_empty_nodes() {
+        let mut result: Tree<()> = Tree::default();
+        result.add_phrase(vec!["a".to_string(), "b".to_string()]);
+
+        let removed = result.remove_phrase(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(removed, Ok(vec!["a".to_string(), "b".to_string()]));
+        assert_eq!(result.children_names().len(), 0);
+    }
+
+    #[test]
+    fn it_prunes_only_up_to_a_node_still_in_use() {
+        let mut result: Tree<()> = Tree::default();
+        result.add_phrase(vec!["a".to_string(), "b".to_string()]);
+        result.add_phrase(vec!["a".to_string(), "c".to_string()]);
+
+        result
+            .remove_phrase(vec!["a".to_string(), "b".to_string()])
+            .unwrap();
+
+        assert_eq!(result.children_names(), vec!["a".to_string()]);
+        let a_tree = result.step_down("a".to_string()).unwrap();
+        assert_eq!(a_tree.children_names(), vec!["c".to_string()]);
+    }
+
+    #[test]
+    fn it_reports_not_found_for_a_missing_phrase() {
+        let mut result: Tree<()> = Tree::default();
+        result.add_phrase(vec!["a".to_string(), "b".to_string()]);
+
+        assert_eq!(
+            result.remove_phrase(vec!["a".to_string(), "z".to_string()]),
+            Err(RemovePhraseError::NotFound)
+        );
+    }
+
+    #[test]
+    fn it_reports_still_has_descendants_for_a_shadowing_prefix() {
+        let mut result: Tree<()> = Tree::default();
+        result.add_phrase(vec!["a".to_string(), "b".to_string(), "c".to_string()]);
+
+        assert_eq!(
+            result.remove_phrase(vec!["a".to_string(), "b".to_string()]),
+            Err(RemovePhraseError::StillHasDescendants)
+        );
+        assert!(result
+            .step_down("a".to_string())
+            .unwrap()
+            .step_down("b".to_string())
+            .is_some());
+    }
+
+    #[test]
+    fn it_flattens_every_stored_path() {
+        let t = Tree::from_corpus("a b. a c.".to_string());
+
+        let mut paths = t.flatten();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                vec!["a".to_string()],
+                vec!["a".to_string(), "b".to_string()],
+                vec!["a".to_string(), "c".to_string()],
+                vec!["b".to_string()],
+                vec!["c".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn it_iterates_paths_lazily_via_the_same_set_flatten_collects() {
+        let t = Tree::from_corpus("a b. a c.".to_string());
+
+        let mut flattened = t.flatten();
+        flattened.sort();
+        let mut iterated: Vec<Vec<String>> = t.iter().collect();
+        iterated.sort();
+
+        assert_eq!(flattened, iterated);
+    }
+
+    #[test]
+    fn it_finds_the_longest_matching_prefix() {
+        let t = Tree::from_corpus("a b c. a b d.".to_string());
+
+        let (matched, names) = t.longest_matching_prefix(vec![
+            "a".to_string(),
+            "b".to_string(),
+            "z".to_string(),
+        ]);
+        assert_eq!(matched, vec!["a".to_string(), "b".to_string()]);
+        let mut names = names;
+        names.sort();
+        assert_eq!(names, vec!["c".to_string(), "d".to_string()]);
+    }
+
+    #[test]
+    fn it_matches_the_whole_path_when_it_is_present() {
+        let t = Tree::from_corpus("a b c.".to_string());
+
+        let (matched, names) =
+            t.longest_matching_prefix(vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(matched, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(names, vec!["c".to_string()]);
+    }
+
+    #[test]
+    fn it_completes_every_continuation_below_a_prefix() {
+        let t = Tree::from_corpus("a b c. a b d.".to_string());
+
+        let mut completions = t.completions(vec!["a".to_string(), "b".to_string()]);
+        completions.sort();
+        assert_eq!(
+            completions,
+            vec![vec!["c".to_string()], vec!["d".to_string()]]
+        );
+    }
+
+    #[test]
+    fn it_has_no_completions_for_an_unmatched_prefix() {
+        let t = Tree::from_corpus("a b c.".to_string());
+
+        assert_eq!(t.completions(vec!["z".to_string()]), Vec::<Vec<String>>::new());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn it_round_trips_through_a_writer_and_reader() {
+        let t = Tree::from_corpus("a b c. a b d.".to_string());
+
+        let mut buf = Vec::new();
+        t.to_writer(&mut buf).unwrap();
+        let reloaded = Tree::from_reader(buf.as_slice()).unwrap();
+
+        assert_eq!(t, reloaded);
+    }
 }
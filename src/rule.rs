@@ -8,30 +8,137 @@ mod rule {
         cartesian_product(dims.into_iter().map(|x| (0..x).collect()).collect())
     }
 
+    /// The notion of "distance" used to order indices by when fanning out
+    /// from the origin. `L1` (sum of coordinates) is the historical default.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum DistanceMetric {
+        L1,
+        Chebyshev,
+        WeightedL1(Vec<usize>),
+        SquaredEuclidean,
+    }
+
+    impl DistanceMetric {
+        fn score(&self, index: &[usize]) -> usize {
+            match self {
+                DistanceMetric::L1 => index.iter().sum(),
+                DistanceMetric::Chebyshev => index.iter().copied().max().unwrap_or(0),
+                DistanceMetric::WeightedL1(weights) => {
+                    index.iter().zip(weights).map(|(c, w)| c * w).sum()
+                }
+                DistanceMetric::SquaredEuclidean => index.iter().map(|c| c * c).sum(),
+            }
+        }
+    }
+
+    fn lexicographic_cmp(a: &[usize], b: &[usize]) -> Ordering {
+        for (x, y) in a.iter().zip(b) {
+            match x.cmp(y) {
+                Ordering::Equal => continue,
+                other => return other,
+            }
+        }
+        Ordering::Equal
+    }
+
+    fn compare_by(metric: &DistanceMetric, a: &[usize], b: &[usize]) -> Ordering {
+        metric
+            .score(a)
+            .cmp(&metric.score(b))
+            .then_with(|| lexicographic_cmp(a, b))
+    }
+
+    /// Orders `indices` by `metric`, breaking ties lexicographically the same
+    /// way `order_by_distance` always has.
+    pub fn order_by(indices: Vec<Vec<usize>>, metric: DistanceMetric) -> Vec<Vec<usize>> {
+        let mut sorted = indices;
+        sorted.sort_by(|a, b| compare_by(&metric, a, b));
+        sorted
+    }
+
     fn order_by_distance(indices: Vec<Vec<usize>>) -> Vec<Vec<usize>> {
-        let mut sorted = indices.clone();
-        sorted.sort_by(|a, b| {
-            let total_a: usize = a.iter().sum();
-            let total_b = b.iter().sum();
-            if total_a > total_b {
-                Ordering::Greater
-            } else if total_a < total_b {
-                Ordering::Less
-            } else {
-                // distance is equal, and so order by each
-                for (x, y) in a.iter().zip(b) {
-                    if x > y {
-                        return Ordering::Greater;
-                    }
+        order_by(indices, DistanceMetric::L1)
+    }
+
+    /// Parallel counterpart to `order_by_distance`, sorting with the same
+    /// sum-then-lexicographic comparator via rayon's parallel sort.
+    #[cfg(feature = "rayon")]
+    pub fn par_order_by_distance(indices: Vec<Vec<usize>>) -> Vec<Vec<usize>> {
+        use rayon::slice::ParallelSliceMut;
+
+        let mut sorted = indices;
+        sorted.par_sort_by(|a, b| compare_by(&DistanceMetric::L1, a, b));
+        sorted
+    }
 
-                    if x < y {
-                        return Ordering::Less;
+    /// Parallel counterpart to `cartesian_product`: the first axis is split
+    /// across threads and each partition builds its own tuples independently
+    /// before they are concatenated.
+    #[cfg(feature = "rayon")]
+    pub fn par_cartesian_product<T: Clone + Send + Sync>(lists: Vec<Vec<T>>) -> Vec<Vec<T>> {
+        use rayon::prelude::*;
+
+        match lists.split_first() {
+            Some((first, [])) => first.par_iter().cloned().map(|value| vec![value]).collect(),
+            Some((first, rest)) => {
+                let rest = rest.to_vec();
+                first
+                    .par_iter()
+                    .flat_map(|value| {
+                        cartesian_product(rest.clone())
+                            .into_iter()
+                            .map(|mut tuple| {
+                                tuple.insert(0, value.clone());
+                                tuple
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .collect()
+            }
+            None => vec![],
+        }
+    }
+
+    /// Emits the same indices as `order_by_distance` (grouped into ascending
+    /// Manhattan-distance shells, lexicographic within a shell) without ever
+    /// materializing or sorting the full index matrix.
+    pub fn shells_by_distance(dims: Vec<usize>) -> Box<dyn Iterator<Item = Vec<usize>>> {
+        if dims.is_empty() {
+            return Box::new(std::iter::empty());
+        }
+        let max_sum: usize = dims.iter().map(|d| d.saturating_sub(1)).sum();
+        Box::new((0..=max_sum).flat_map(move |s| shell(&dims, s)))
+    }
+
+    fn shell(dims: &[usize], sum: usize) -> Vec<Vec<usize>> {
+        let mut acc = vec![];
+        compose(dims, sum, &mut vec![], &mut acc);
+        acc
+    }
+
+    fn compose(dims: &[usize], remaining: usize, prefix: &mut Vec<usize>, acc: &mut Vec<Vec<usize>>) {
+        match dims.split_first() {
+            None => {
+                if remaining == 0 {
+                    acc.push(prefix.clone());
+                }
+            }
+            Some((&dim, rest)) => {
+                let rest_max: usize = rest.iter().map(|d| d.saturating_sub(1)).sum();
+                for c in 0..dim {
+                    if c > remaining {
+                        break;
+                    }
+                    let residual = remaining - c;
+                    if residual > rest_max {
+                        continue;
                     }
+                    prefix.push(c);
+                    compose(rest, residual, prefix, acc);
+                    prefix.pop();
                 }
-                unreachable!("There should not be duplicate indices")
             }
-        });
-        sorted
+        }
     }
 
     pub fn partial_cartesian<T: Clone>(a: Vec<Vec<T>>, b: Vec<T>) -> Vec<Vec<T>> {
@@ -49,26 +156,154 @@ mod rule {
             .collect()
     }
 
+    /// Lazily yields every tuple of the cartesian product of `lists`, one
+    /// combination at a time, like an odometer: the last axis advances
+    /// fastest, and an overflowing axis resets to zero and carries into the
+    /// one before it.
+    pub struct MultiProduct<T> {
+        lists: Vec<Vec<T>>,
+        indices: Vec<usize>,
+        done: bool,
+    }
+
+    impl<T: Clone> MultiProduct<T> {
+        pub fn new(lists: Vec<Vec<T>>) -> Self {
+            let done = lists.is_empty() || lists.iter().any(|list| list.is_empty());
+            let indices = vec![0; lists.len()];
+            MultiProduct {
+                lists,
+                indices,
+                done,
+            }
+        }
+    }
+
+    impl<T: Clone> Iterator for MultiProduct<T> {
+        type Item = Vec<T>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.done {
+                return None;
+            }
+
+            let current = self
+                .indices
+                .iter()
+                .zip(&self.lists)
+                .map(|(&i, list)| list[i].clone())
+                .collect();
+
+            let mut axis = self.lists.len();
+            loop {
+                if axis == 0 {
+                    self.done = true;
+                    break;
+                }
+                axis -= 1;
+                self.indices[axis] += 1;
+                if self.indices[axis] < self.lists[axis].len() {
+                    break;
+                }
+                self.indices[axis] = 0;
+                if axis == 0 {
+                    self.done = true;
+                    break;
+                }
+            }
+
+            Some(current)
+        }
+    }
+
     pub fn cartesian_product<T: Clone>(lists: Vec<Vec<T>>) -> Vec<Vec<T>> {
-        match lists.split_first() {
-            Some((first, rest)) => {
-                let init: Vec<Vec<T>> = first.iter().cloned().map(|n| vec![n]).collect();
+        MultiProduct::new(lists).collect()
+    }
 
-                rest.iter()
-                    .cloned()
-                    .fold(init, |vec, list| partial_cartesian(vec, list))
+    struct Combinations {
+        n: usize,
+        k: usize,
+        replacement: bool,
+        indices: Vec<usize>,
+        done: bool,
+    }
+
+    impl Combinations {
+        fn new(n: usize, k: usize, replacement: bool) -> Self {
+            let done = (k > 0 && n == 0) || (!replacement && k > n);
+            let indices = if replacement { vec![0; k] } else { (0..k).collect() };
+            Combinations {
+                n,
+                k,
+                replacement,
+                indices,
+                done,
             }
-            None => {
-                vec![]
+        }
+
+        fn ceiling(&self, pos: usize) -> usize {
+            if self.replacement {
+                self.n - 1
+            } else {
+                self.n - (self.k - pos)
             }
         }
     }
 
+    impl Iterator for Combinations {
+        type Item = Vec<usize>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.done {
+                return None;
+            }
+
+            let current = self.indices.clone();
+
+            if self.k == 0 {
+                self.done = true;
+                return Some(current);
+            }
+
+            let mut pos = self.k;
+            loop {
+                if pos == 0 {
+                    self.done = true;
+                    break;
+                }
+                pos -= 1;
+                if self.indices[pos] < self.ceiling(pos) {
+                    self.indices[pos] += 1;
+                    for j in (pos + 1)..self.k {
+                        self.indices[j] = if self.replacement {
+                            self.indices[pos]
+                        } else {
+                            self.indices[j - 1] + 1
+                        };
+                    }
+                    break;
+                }
+            }
+
+            Some(current)
+        }
+    }
+
+    pub fn combinations(n: usize, k: usize) -> impl Iterator<Item = Vec<usize>> {
+        Combinations::new(n, k, false)
+    }
+
+    pub fn combinations_with_replacement(n: usize, k: usize) -> impl Iterator<Item = Vec<usize>> {
+        Combinations::new(n, k, true)
+    }
+
     #[cfg(test)]
     mod tests {
         use crate::rule::rule::order_by_distance;
 
-        use super::index_array;
+        use super::{
+            cartesian_product, combinations, combinations_with_replacement, index_array,
+            order_by, shells_by_distance, DistanceMetric, MultiProduct,
+        };
 
         #[test]
         fn it_produces_an_index_matrix_with_dims() {
@@ -101,6 +336,119 @@ mod rule {
             );
         }
 
+        #[test]
+        fn it_lazily_yields_the_cartesian_product() {
+            let mut product = MultiProduct::new(vec![vec![0, 1], vec![10, 20]]);
+            assert_eq!(product.next(), Some(vec![0, 10]));
+            assert_eq!(product.next(), Some(vec![0, 20]));
+            assert_eq!(product.next(), Some(vec![1, 10]));
+            assert_eq!(product.next(), Some(vec![1, 20]));
+            assert_eq!(product.next(), None);
+        }
+
+        #[test]
+        fn it_produces_no_items_for_empty_lists() {
+            assert_eq!(MultiProduct::<usize>::new(vec![]).next(), None);
+            assert_eq!(MultiProduct::new(vec![vec![1, 2], vec![]]).next(), None);
+        }
+
+        #[test]
+        fn it_collects_the_same_product_as_the_eager_function() {
+            assert_eq!(
+                cartesian_product(vec![vec![0, 1], vec![10, 20]]),
+                vec![vec![0, 10], vec![0, 20], vec![1, 10], vec![1, 20]]
+            );
+        }
+
+        #[test]
+        fn it_streams_indices_in_the_same_order_as_order_by_distance() {
+            let dims = vec![3, 3];
+            assert_eq!(
+                shells_by_distance(dims.clone()).collect::<Vec<_>>(),
+                order_by_distance(index_array(dims))
+            );
+        }
+
+        #[test]
+        fn it_streams_no_indices_for_empty_dims() {
+            assert_eq!(shells_by_distance(vec![]).collect::<Vec<_>>(), Vec::<Vec<usize>>::new());
+        }
+
+        #[test]
+        fn it_streams_no_indices_when_an_axis_is_zero_length() {
+            assert_eq!(
+                shells_by_distance(vec![3, 0]).collect::<Vec<_>>(),
+                Vec::<Vec<usize>>::new()
+            );
+        }
+
+        #[cfg(feature = "rayon")]
+        #[test]
+        fn it_parallel_sorts_the_same_as_order_by_distance() {
+            use super::par_order_by_distance;
+
+            let indices = index_array(vec![3, 3]);
+            assert_eq!(
+                par_order_by_distance(indices.clone()),
+                order_by_distance(indices)
+            );
+        }
+
+        #[cfg(feature = "rayon")]
+        #[test]
+        fn it_parallel_products_the_same_as_cartesian_product() {
+            use super::par_cartesian_product;
+
+            let lists = vec![vec![0, 1], vec![10, 20]];
+            let mut parallel = par_cartesian_product(lists.clone());
+            let mut serial = cartesian_product(lists);
+            parallel.sort();
+            serial.sort();
+            assert_eq!(parallel, serial);
+        }
+
+        #[test]
+        fn it_orders_by_l1_the_same_as_order_by_distance() {
+            let indices = index_array(vec![2, 2]);
+            assert_eq!(
+                order_by(indices.clone(), DistanceMetric::L1),
+                order_by_distance(indices)
+            );
+        }
+
+        #[test]
+        fn it_orders_by_chebyshev_distance() {
+            assert_eq!(
+                order_by(
+                    vec![vec![0, 0], vec![0, 2], vec![1, 1]],
+                    DistanceMetric::Chebyshev
+                ),
+                vec![vec![0, 0], vec![1, 1], vec![0, 2]]
+            );
+        }
+
+        #[test]
+        fn it_orders_by_weighted_l1_distance() {
+            assert_eq!(
+                order_by(
+                    vec![vec![2, 0], vec![0, 1]],
+                    DistanceMetric::WeightedL1(vec![1, 5])
+                ),
+                vec![vec![2, 0], vec![0, 1]]
+            );
+        }
+
+        #[test]
+        fn it_orders_by_squared_euclidean_distance() {
+            assert_eq!(
+                order_by(
+                    vec![vec![0, 2], vec![1, 1]],
+                    DistanceMetric::SquaredEuclidean
+                ),
+                vec![vec![1, 1], vec![0, 2]]
+            );
+        }
+
         #[test]
         fn it_orders_indices() {
             assert_eq!(
@@ -128,5 +476,65 @@ mod rule {
                 ]
             );
         }
+
+        #[test]
+        fn it_enumerates_combinations() {
+            assert_eq!(
+                combinations(4, 2).collect::<Vec<_>>(),
+                vec![
+                    vec![0, 1],
+                    vec![0, 2],
+                    vec![0, 3],
+                    vec![1, 2],
+                    vec![1, 3],
+                    vec![2, 3],
+                ]
+            );
+        }
+
+        #[test]
+        fn it_yields_a_single_empty_vector_for_k_zero() {
+            assert_eq!(
+                combinations(4, 0).collect::<Vec<_>>(),
+                vec![Vec::<usize>::new()]
+            );
+            assert_eq!(
+                combinations_with_replacement(4, 0).collect::<Vec<_>>(),
+                vec![Vec::<usize>::new()]
+            );
+        }
+
+        #[test]
+        fn it_yields_nothing_when_k_exceeds_n() {
+            assert_eq!(combinations(2, 3).collect::<Vec<_>>(), Vec::<Vec<usize>>::new());
+        }
+
+        #[test]
+        fn it_enumerates_combinations_with_replacement() {
+            assert_eq!(
+                combinations_with_replacement(3, 2).collect::<Vec<_>>(),
+                vec![
+                    vec![0, 0],
+                    vec![0, 1],
+                    vec![0, 2],
+                    vec![1, 1],
+                    vec![1, 2],
+                    vec![2, 2],
+                ]
+            );
+        }
+
+        #[test]
+        fn it_allows_k_to_exceed_n_with_replacement() {
+            assert_eq!(
+                combinations_with_replacement(2, 3).collect::<Vec<_>>(),
+                vec![
+                    vec![0, 0, 0],
+                    vec![0, 0, 1],
+                    vec![0, 1, 1],
+                    vec![1, 1, 1],
+                ]
+            );
+        }
     }
 }